@@ -1,13 +1,146 @@
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+
 use image::{DynamicImage, GenericImage, Rgba};
 
 use config::{Mode, Orientation};
 use energy::PixelEnergyPoint;
 use grid::Grid;
 
+/// Selects how a seam is charged for removing a pixel.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EnergyModel {
+    /// The removed pixel's own energy from the carver's `EnergyFunction`
+    /// (squared horizontal/vertical gradients by default).
+    Backward,
+    /// Charges each seam with the new pixel adjacencies it creates, avoiding
+    /// the discontinuities backward energy leaves behind.
+    Forward,
+}
+
+/// Computes a single pixel's importance for `EnergyModel::Backward`; higher
+/// means less likely to be carved. Swapping the implementation trades speed
+/// for quality per image without touching the carving machinery itself.
+pub trait EnergyFunction {
+    fn energy(&self, grid: &Grid<PixelEnergyPoint>, x: usize, y: usize) -> usize;
+}
+
+/// The original operator: sum of squared horizontal/vertical gradients.
+pub struct SquaredGradientEnergy;
+
+impl EnergyFunction for SquaredGradientEnergy {
+    fn energy(&self, grid: &Grid<PixelEnergyPoint>, x: usize, y: usize) -> usize {
+        let (left, right, up, down) = grid.get_adjacent(x, y);
+        left.square_gradient(right) + up.square_gradient(down)
+    }
+}
+
+/// 3x3 Sobel convolution on luminance; tends to highlight edges more
+/// cleanly than a plain squared gradient at the cost of a 9-pixel sample.
+pub struct SobelEnergy;
+
+const SOBEL_X: [[i32; 3]; 3] = [[-1, 0, 1], [-2, 0, 2], [-1, 0, 1]];
+const SOBEL_Y: [[i32; 3]; 3] = [[-1, -2, -1], [0, 0, 0], [1, 2, 1]];
+
+impl EnergyFunction for SobelEnergy {
+    fn energy(&self, grid: &Grid<PixelEnergyPoint>, x: usize, y: usize) -> usize {
+        let mut sum_x = 0i32;
+        let mut sum_y = 0i32;
+        for (j, dy) in (-1isize..=1).enumerate() {
+            for (i, dx) in (-1isize..=1).enumerate() {
+                let sample = luminance(clamped_get(grid, x, y, dx, dy)) as i32;
+                sum_x += SOBEL_X[j][i] * sample;
+                sum_y += SOBEL_Y[j][i] * sample;
+            }
+        }
+        ((sum_x * sum_x + sum_y * sum_y) as f64).sqrt() as usize
+    }
+}
+
+/// Local luminance variance in a `(2 * window_radius + 1)`-wide window;
+/// busy, textured regions score high, so like any high-energy pixel they
+/// are avoided by seams rather than carved first.
+pub struct VarianceEnergy {
+    pub window_radius: usize,
+}
+
+impl Default for VarianceEnergy {
+    fn default() -> Self {
+        VarianceEnergy { window_radius: 1 }
+    }
+}
+
+impl EnergyFunction for VarianceEnergy {
+    fn energy(&self, grid: &Grid<PixelEnergyPoint>, x: usize, y: usize) -> usize {
+        let radius = self.window_radius as isize;
+        let mut samples = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1)) as usize);
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                samples.push(luminance(clamped_get(grid, x, y, dx, dy)) as f64);
+            }
+        }
+
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() /
+                       samples.len() as f64;
+        variance as usize
+    }
+}
+
+/// Named energy-function choices, so callers driven by `config` can pick one
+/// the same way they pick `Mode`/`Orientation` instead of constructing an
+/// `Rc<dyn EnergyFunction>` by hand.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EnergyFunctionChoice {
+    SquaredGradient,
+    Sobel,
+    Variance { window_radius: usize },
+}
+
+impl EnergyFunctionChoice {
+    fn build(self) -> Rc<dyn EnergyFunction> {
+        match self {
+            EnergyFunctionChoice::SquaredGradient => Rc::new(SquaredGradientEnergy),
+            EnergyFunctionChoice::Sobel => Rc::new(SobelEnergy),
+            EnergyFunctionChoice::Variance { window_radius } => {
+                Rc::new(VarianceEnergy { window_radius })
+            }
+        }
+    }
+}
+
+fn clamped_get(grid: &Grid<PixelEnergyPoint>, x: usize, y: usize, dx: isize, dy: isize) -> Rgba<u8> {
+    let cx = (x as isize + dx).max(0).min(grid.width() as isize - 1) as usize;
+    let cy = (y as isize + dy).max(0).min(grid.height() as isize - 1) as usize;
+    grid.get(cx, cy).pixel
+}
+
+fn luminance(pixel: Rgba<u8>) -> u32 {
+    let data = pixel.data;
+    (0.299 * data[0] as f64 + 0.587 * data[1] as f64 + 0.114 * data[2] as f64) as u32
+}
+
+// Added to a pixel's energy when it falls under the protect mask, or
+// subtracted when it falls under the remove mask, so seams steer clear of
+// or are funneled through the marked region respectively.
+const MASK_ENERGY_BIAS: usize = 1_000_000;
+
 #[derive(Clone)]
 pub struct Carver {
     grid: Grid<PixelEnergyPoint>,
     removed_points: Vec<(usize, usize)>,
+    energy_model: EnergyModel,
+    forward_parents: Vec<Vec<i8>>,
+    protect_mask: Vec<Vec<bool>>,
+    remove_mask: Vec<Vec<bool>>,
+    energy_computed: bool,
+    dirty_cells: HashSet<(usize, usize)>,
+    energy_function: Rc<dyn EnergyFunction>,
+    record_frames: bool,
+    overlay_frame_seams: bool,
+    frames: Vec<DynamicImage>,
+    rotated: bool,
 }
 
 impl Carver {
@@ -16,7 +149,79 @@ impl Carver {
         Self {
             grid,
             removed_points: vec![],
+            energy_model: EnergyModel::Backward,
+            forward_parents: vec![],
+            protect_mask: vec![],
+            remove_mask: vec![],
+            energy_computed: false,
+            dirty_cells: HashSet::new(),
+            energy_function: Rc::new(SquaredGradientEnergy),
+            record_frames: false,
+            overlay_frame_seams: false,
+            frames: vec![],
+            rotated: false,
+        }
+    }
+
+    pub fn set_energy_function(&mut self, energy_function: Rc<dyn EnergyFunction>) {
+        self.energy_function = energy_function;
+        self.energy_computed = false;
+        self.dirty_cells.clear();
+    }
+
+    /// Selects a built-in energy function by name, for callers that plumb
+    /// the choice through `config` rather than constructing one directly.
+    pub fn set_energy_function_choice(&mut self, choice: EnergyFunctionChoice) {
+        self.set_energy_function(choice.build());
+    }
+
+    /// Enables capturing a frame before every seam removal, for assembling
+    /// a step-by-step view of the carving process. `overlay_seam` draws the
+    /// about-to-be-removed seam in red on each frame via `create_debug_image`.
+    pub fn set_record_frames(&mut self, record: bool, overlay_seam: bool) {
+        self.record_frames = record;
+        self.overlay_frame_seams = overlay_seam;
+        self.frames.clear();
+    }
+
+    pub fn get_frames(&self) -> Vec<DynamicImage> {
+        self.frames.clone()
+    }
+
+    pub fn new_with_masks(image: &DynamicImage,
+                           protect_points: &[(usize, usize)],
+                           remove_points: &[(usize, usize)])
+                           -> Self {
+        let mut carver = Self::new(image);
+        carver.set_protect_mask(protect_points);
+        carver.set_remove_mask(remove_points);
+        carver
+    }
+
+    pub fn set_energy_model(&mut self, energy_model: EnergyModel) {
+        self.energy_model = energy_model;
+        self.energy_computed = false;
+        self.dirty_cells.clear();
+    }
+
+    pub fn set_protect_mask(&mut self, points: &[(usize, usize)]) {
+        self.protect_mask = Self::mask_from_points(self.grid.width(), self.grid.height(), points);
+        self.energy_computed = false;
+        self.dirty_cells.clear();
+    }
+
+    pub fn set_remove_mask(&mut self, points: &[(usize, usize)]) {
+        self.remove_mask = Self::mask_from_points(self.grid.width(), self.grid.height(), points);
+        self.energy_computed = false;
+        self.dirty_cells.clear();
+    }
+
+    fn mask_from_points(width: usize, height: usize, points: &[(usize, usize)]) -> Vec<Vec<bool>> {
+        let mut mask = vec![vec![false; width]; height];
+        for &(x, y) in points {
+            mask[y][x] = true;
         }
+        mask
     }
 
     pub fn resize(&mut self,
@@ -28,9 +233,46 @@ impl Carver {
         match orientation {
             Orientation::Horizontal => self.resize_distance(distance, mode),
             Orientation::Vertical => {
-                self.grid.rotate();
+                self.rotate();
                 self.resize_distance(distance, mode);
-                self.grid.rotate();
+                self.rotate();
+            }
+        }
+
+        self.rebuild_image()
+    }
+
+    /// Shrinks both dimensions at once along the globally optimal
+    /// interleaving of horizontal and vertical seam removals, rather than
+    /// exhausting one orientation before starting the other.
+    ///
+    /// Quality comes at a real cost: every cell of the `(Δheight+1) x
+    /// (Δwidth+1)` transport map clones the whole `Carver` and evaluates a
+    /// candidate seam on it, and any cell reached via a horizontal-seam
+    /// candidate forces a full grid recompute (`rotate` invalidates the
+    /// dirty-cell cache from chunk0-4), so the total cost scales with
+    /// `Δheight * Δwidth * width * height` rather than the `O(width *
+    /// height)` per seam that shrinking one axis at a time pays. For large
+    /// resizes, prefer two sequential `resize` calls (one per orientation)
+    /// unless the better seam ordering is worth the quadratic blowup.
+    pub fn resize_2d(&mut self, target_width: usize, target_height: usize) -> DynamicImage {
+        let current_width = self.grid.width();
+        let current_height = self.grid.height();
+
+        if target_width <= current_width && target_height <= current_height {
+            let vertical_seams = current_width - target_width;
+            let horizontal_seams = current_height - target_height;
+            self.shrink_2d(horizontal_seams, vertical_seams);
+        } else {
+            if target_width != current_width {
+                let distance = (target_width as isize - current_width as isize).abs() as usize;
+                let mode = if target_width < current_width { Mode::Shrink } else { Mode::Grow };
+                self.resize(distance, Orientation::Horizontal, mode);
+            }
+            if target_height != current_height {
+                let distance = (target_height as isize - current_height as isize).abs() as usize;
+                let mode = if target_height < current_height { Mode::Shrink } else { Mode::Grow };
+                self.resize(distance, Orientation::Vertical, mode);
             }
         }
 
@@ -60,6 +302,9 @@ impl Carver {
             let pixel = self.average_pixel_from_neighbors(x, y, left);
             self.add_point(x, y, pixel)
         }
+
+        self.energy_computed = false;
+        self.dirty_cells.clear();
     }
 
     fn get_points_removed_by_shrink(&self, distance: usize) -> Vec<(usize, usize)> {
@@ -79,29 +324,208 @@ impl Carver {
             self.calculate_energy();
             let (start_x, start_y) = self.get_path_start();
             let path = self.find_path(start_x, start_y);
+            self.capture_frame(&path);
             self.remove_path(path);
         }
     }
 
+    // Mid-seam this may be called while `rotate()` has us in a transposed
+    // orientation (e.g. mid-way through a height-changing `resize`), so the
+    // frame and the overlaid `path` — both still in that transposed
+    // coordinate space at this point — are untransposed together before
+    // being stored, keeping every recorded frame in the original image's
+    // orientation.
+    fn capture_frame(&mut self, path: &[(usize, usize)]) {
+        if !self.record_frames {
+            return;
+        }
+        let frame = self.rebuild_image();
+        let frame = if self.overlay_frame_seams {
+            create_debug_image(&frame, path)
+        } else {
+            frame
+        };
+        let frame = if self.rotated { transpose_image(&frame) } else { frame };
+        self.frames.push(frame);
+    }
+
+    // Builds the transport map T(r,c): the cheapest total cost of removing r
+    // horizontal and c vertical seams in some interleaved order. Only costs
+    // and a per-cell "which direction was cheaper" bit are kept across the
+    // full table; the two rolling rows of live grid states needed to measure
+    // each step's cost are dropped once the row below has been computed.
+    fn shrink_2d(&mut self, horizontal_seams: usize, vertical_seams: usize) {
+        let rows = horizontal_seams + 1;
+        let cols = vertical_seams + 1;
+
+        let mut cost_table = vec![vec![0usize; cols]; rows];
+        let mut via_horizontal_seam = vec![vec![false; cols]; rows];
+
+        let mut prev_row = Vec::with_capacity(cols);
+        let mut state = self.clone();
+        prev_row.push(state.clone());
+        for c in 1..cols {
+            let (cost, next_state) = state.remove_cheapest_seam(Orientation::Horizontal);
+            cost_table[0][c] = cost_table[0][c - 1] + cost;
+            state = next_state;
+            prev_row.push(state.clone());
+        }
+
+        for r in 1..rows {
+            let mut cur_row = Vec::with_capacity(cols);
+
+            let (cost, next_state) = prev_row[0].remove_cheapest_seam(Orientation::Vertical);
+            cost_table[r][0] = cost_table[r - 1][0] + cost;
+            via_horizontal_seam[r][0] = true;
+            cur_row.push(next_state);
+
+            for c in 1..cols {
+                let (from_above_cost, from_above_state) =
+                    prev_row[c].remove_cheapest_seam(Orientation::Vertical);
+                let from_above_total = cost_table[r - 1][c] + from_above_cost;
+
+                let (from_left_cost, from_left_state) =
+                    cur_row[c - 1].remove_cheapest_seam(Orientation::Horizontal);
+                let from_left_total = cost_table[r][c - 1] + from_left_cost;
+
+                if from_above_total <= from_left_total {
+                    cost_table[r][c] = from_above_total;
+                    via_horizontal_seam[r][c] = true;
+                    cur_row.push(from_above_state);
+                } else {
+                    cost_table[r][c] = from_left_total;
+                    via_horizontal_seam[r][c] = false;
+                    cur_row.push(from_left_state);
+                }
+            }
+
+            prev_row = cur_row;
+        }
+
+        let mut schedule = Vec::with_capacity(horizontal_seams + vertical_seams);
+        let (mut r, mut c) = (rows - 1, cols - 1);
+        while r > 0 || c > 0 {
+            if via_horizontal_seam[r][c] {
+                schedule.push(Orientation::Vertical);
+                r -= 1;
+            } else {
+                schedule.push(Orientation::Horizontal);
+                c -= 1;
+            }
+        }
+        schedule.reverse();
+
+        for orientation in schedule {
+            self.resize(1, orientation, Mode::Shrink);
+        }
+    }
+
+    // Removes the single cheapest seam in `orientation` from a clone of
+    // self, returning its cost alongside the resulting state.
+    fn remove_cheapest_seam(&self, orientation: Orientation) -> (usize, Carver) {
+        let mut next = self.clone();
+        if let Orientation::Vertical = orientation {
+            next.rotate();
+        }
+
+        next.calculate_energy();
+        let (start_x, start_y) = next.get_path_start();
+        let cost = next.grid.get(start_x, start_y).path_cost;
+        let path = next.find_path(start_x, start_y);
+        next.remove_path(path);
+
+        if let Orientation::Vertical = orientation {
+            next.rotate();
+        }
+
+        (cost, next)
+    }
+
+    // Rotating reinterprets every pixel's up/down/left/right adjacency, so
+    // any energy or path cost computed under the old orientation is invalid
+    // afterwards.
+    fn rotate(&mut self) {
+        self.grid.rotate();
+        self.rotate_masks();
+        self.energy_computed = false;
+        self.dirty_cells.clear();
+        self.rotated = !self.rotated;
+    }
+
     fn calculate_energy(&mut self) {
-        for y in 0..self.grid.height() {
+        match self.energy_model {
+            EnergyModel::Forward => {
+                self.forward_parents = vec![vec![0; self.grid.width()]; self.grid.height()];
+                for y in 0..self.grid.height() {
+                    for x in 0..self.grid.width() {
+                        self.calculate_forward_path_cost(x, y);
+                    }
+                }
+                // Forward mode doesn't maintain the Backward energy cache, so
+                // a later switch back to Backward must force a full recompute
+                // rather than trusting stale dirty-cell bookkeeping.
+                self.energy_computed = false;
+                self.dirty_cells.clear();
+            }
+            EnergyModel::Backward => self.calculate_energy_backward(),
+        }
+    }
+
+    // Recomputes pixel energy for the whole grid only the first time this is
+    // called; afterwards, `remove_path` has already recorded exactly which
+    // cells' adjacencies changed, so only those need their energy redone.
+    // Path cost still has to propagate top-to-bottom, but the sweep only
+    // needs to start at the topmost row any dirty cell touched.
+    fn calculate_energy_backward(&mut self) {
+        let min_dirty_row = self.dirty_cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+
+        if !self.energy_computed {
+            for y in 0..self.grid.height() {
+                for x in 0..self.grid.width() {
+                    self.calculate_pixel_energy(x, y);
+                }
+            }
+            self.energy_computed = true;
+        } else {
+            let dirty = mem::replace(&mut self.dirty_cells, HashSet::new());
+            for (x, y) in dirty {
+                if x < self.grid.width() && y < self.grid.height() {
+                    self.calculate_pixel_energy(x, y);
+                }
+            }
+        }
+
+        for y in min_dirty_row..self.grid.height() {
             for x in 0..self.grid.width() {
-                self.calculate_pixel_energy(x, y);
                 self.calculate_path_cost(x, y);
             }
         }
     }
 
     fn calculate_pixel_energy(&mut self, x: usize, y: usize) {
-        let energy = {
-            let (left, right, up, down) = self.grid.get_adjacent(x, y);
-            let horizontal_square_gradient = left.square_gradient(right);
-            let vertical_square_gradient = up.square_gradient(down);
-            horizontal_square_gradient + vertical_square_gradient
-        };
+        let energy_function = Rc::clone(&self.energy_function);
+        let mut energy = energy_function.energy(&self.grid, x, y);
+        if self.is_protected(x, y) {
+            energy += MASK_ENERGY_BIAS;
+        }
+        if self.is_marked_for_removal(x, y) {
+            energy = energy.saturating_sub(MASK_ENERGY_BIAS);
+        }
         self.grid.get_mut(x, y).energy = energy;
     }
 
+    fn is_protected(&self, x: usize, y: usize) -> bool {
+        Self::mask_at(&self.protect_mask, x, y)
+    }
+
+    fn is_marked_for_removal(&self, x: usize, y: usize) -> bool {
+        Self::mask_at(&self.remove_mask, x, y)
+    }
+
+    fn mask_at(mask: &[Vec<bool>], x: usize, y: usize) -> bool {
+        mask.get(y).and_then(|row| row.get(x)).cloned().unwrap_or(false)
+    }
+
     fn calculate_path_cost(&mut self, x: usize, y: usize) {
         let min_parent_path_cost = self.get_min_parent_path_cost(x, y).unwrap_or(0);
         let energy = self.grid.get(x, y).energy;
@@ -116,11 +540,59 @@ impl Carver {
             .min()
     }
 
+    // Charges M(x,y) with whichever of C_left/C_up/C_right the chosen parent
+    // produces, and remembers that choice so find_path can backtrack along
+    // the same parent rather than re-deriving it from path_cost alone.
+    fn calculate_forward_path_cost(&mut self, x: usize, y: usize) {
+        let (left, right, up, _down) = self.grid.get_adjacent(x, y);
+        let c_up = pixel_gradient(left.pixel, right.pixel);
+        let c_left = c_up + pixel_gradient(up.pixel, left.pixel);
+        let c_right = c_up + pixel_gradient(up.pixel, right.pixel);
+
+        let mut best: Option<(usize, i8)> = None;
+        for (px, _py, parent) in self.grid.get_parents(x, y) {
+            let dx = px as isize - x as isize;
+            let directional_cost = match dx {
+                -1 => c_left,
+                0 => c_up,
+                1 => c_right,
+                _ => continue,
+            };
+            let candidate = parent.path_cost + directional_cost;
+            if best.map_or(true, |(cost, _)| candidate < cost) {
+                best = Some((candidate, dx as i8));
+            }
+        }
+
+        let (mut path_cost, parent_dx) = best.unwrap_or((0, 0));
+        if self.is_protected(x, y) {
+            path_cost += MASK_ENERGY_BIAS;
+        }
+        if self.is_marked_for_removal(x, y) {
+            path_cost = path_cost.saturating_sub(MASK_ENERGY_BIAS);
+        }
+
+        self.grid.get_mut(x, y).path_cost = path_cost;
+        self.forward_parents[y][x] = parent_dx;
+    }
+
+    fn get_forward_parent(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if y == 0 {
+            return None;
+        }
+        let dx = self.forward_parents[y][x] as isize;
+        Some(((x as isize + dx) as usize, y - 1))
+    }
+
     fn find_path(&self, start_x: usize, start_y: usize) -> Vec<(usize, usize)> {
         let mut path = vec![(start_x, start_y)];
         loop {
             let &(x, y) = path.last().unwrap();
-            match self.get_parent_with_min_path_cost(x, y) {
+            let parent = match self.energy_model {
+                EnergyModel::Backward => self.get_parent_with_min_path_cost(x, y),
+                EnergyModel::Forward => self.get_forward_parent(x, y),
+            };
+            match parent {
                 None => return path,
                 Some(parent) => path.push(parent),
             }
@@ -149,6 +621,14 @@ impl Carver {
     fn add_point(&mut self, x: usize, y: usize, pixel: Rgba<u8>) {
         self.grid.shift_row_right_from_point(x, y);
         *self.grid.get_mut(x + 1, y) = pixel.into();
+        Self::insert_mask_point(&mut self.protect_mask, x, y);
+        Self::insert_mask_point(&mut self.remove_mask, x, y);
+    }
+
+    fn insert_mask_point(mask: &mut [Vec<bool>], x: usize, y: usize) {
+        if let Some(row) = mask.get_mut(y) {
+            row.insert(x + 1, false);
+        }
     }
 
     fn average_pixel_from_neighbors(&self, x: usize, y: usize, left: Rgba<u8>) -> Rgba<u8> {
@@ -161,10 +641,54 @@ impl Carver {
         for (x, y) in points {
             self.removed_points.push((x, y));
             self.grid.shift_row_left_from_point(x, y);
+            Self::remove_mask_point(&mut self.protect_mask, x, y);
+            Self::remove_mask_point(&mut self.remove_mask, x, y);
+            self.mark_dirty_around(x, y);
         }
         self.grid.remove_last_column();
     }
 
+    // Only the pixels that gained a new left/right neighbor when column `x`
+    // absorbed the shift (and their up/down neighbors, since pixel energy
+    // also depends on the vertical gradient) can have changed energy.
+    fn mark_dirty_around(&mut self, x: usize, y: usize) {
+        let max_col = (x + 1).min(self.grid.width().saturating_sub(1));
+        let max_row = (y + 1).min(self.grid.height().saturating_sub(1));
+        for col in x.saturating_sub(1)..=max_col {
+            for row in y.saturating_sub(1)..=max_row {
+                self.dirty_cells.insert((col, row));
+            }
+        }
+    }
+
+    fn remove_mask_point(mask: &mut [Vec<bool>], x: usize, y: usize) {
+        if let Some(row) = mask.get_mut(y) {
+            if x < row.len() {
+                row.remove(x);
+            }
+        }
+    }
+
+    fn rotate_masks(&mut self) {
+        self.protect_mask = Self::transpose_mask(&self.protect_mask);
+        self.remove_mask = Self::transpose_mask(&self.remove_mask);
+    }
+
+    fn transpose_mask(mask: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        if mask.is_empty() {
+            return vec![];
+        }
+        let height = mask.len();
+        let width = mask[0].len();
+        let mut transposed = vec![vec![false; height]; width];
+        for y in 0..height {
+            for x in 0..width {
+                transposed[x][y] = mask[y][x];
+            }
+        }
+        transposed
+    }
+
     fn rebuild_image(&self) -> DynamicImage {
         let mut image = DynamicImage::new_rgba8(self.grid.width() as u32,
                                                 self.grid.height() as u32);
@@ -175,6 +699,26 @@ impl Carver {
     }
 }
 
+// `Grid::rotate` is its own inverse (it transposes width/height rather than
+// performing a directional 90-degree turn), so un-transposing an already
+// rotated frame is just rebuilding a grid from it and rotating that once.
+fn transpose_image(image: &DynamicImage) -> DynamicImage {
+    let mut grid: Grid<PixelEnergyPoint> = image.into();
+    grid.rotate();
+
+    let mut transposed = DynamicImage::new_rgba8(grid.width() as u32, grid.height() as u32);
+    for (x, y, pep) in grid.coord_iter() {
+        transposed.put_pixel(x as u32, y as u32, pep.pixel);
+    }
+    transposed
+}
+
+fn pixel_gradient(pixel1: Rgba<u8>, pixel2: Rgba<u8>) -> usize {
+    (0..3)
+        .map(|i| (pixel1.data[i] as i32 - pixel2.data[i] as i32).abs() as usize)
+        .sum()
+}
+
 fn average_pixels(pixel1: &[u8; 4], pixel2: &[u8; 4]) -> [u8; 4] {
     [((pixel1[0] as u16 + pixel2[0] as u16) / 2) as u8,
      ((pixel1[1] as u16 + pixel2[1] as u16) / 2) as u8,
@@ -190,3 +734,237 @@ pub fn create_debug_image(image: &DynamicImage, points: &[(usize, usize)]) -> Dy
     }
     image
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform_image(width: u32, height: u32, pixel: Rgba<u8>) -> DynamicImage {
+        let mut image = DynamicImage::new_rgba8(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.put_pixel(x, y, pixel);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn resize_2d_shrinks_to_the_target_dimensions() {
+        let image = uniform_image(6, 5, Rgba { data: [10, 20, 30, 255] });
+        let mut carver = Carver::new(&image);
+
+        let result = carver.resize_2d(4, 3);
+
+        assert_eq!(result.dimensions(), (4, 3));
+    }
+
+    // Regression test for the transport map in `shrink_2d`: each removed
+    // seam shrinks its axis by exactly one pixel, so however the DP
+    // interleaves horizontal and vertical seams, the total number of
+    // points removed must equal the drop in grid area, not more or less.
+    #[test]
+    fn resize_2d_removes_exactly_the_area_difference() {
+        let image = uniform_image(6, 5, Rgba { data: [10, 20, 30, 255] });
+        let mut carver = Carver::new(&image);
+
+        carver.resize_2d(4, 3);
+
+        assert_eq!(carver.get_removed_points().len(), 6 * 5 - 4 * 3);
+    }
+
+    // Regression test for the forward-energy recurrence: on a uniform
+    // image every gradient term is zero regardless of which neighbor
+    // `calculate_forward_path_cost` samples, so every path cost should
+    // settle at zero rather than accumulating through a sign or
+    // direction bug.
+    #[test]
+    fn forward_energy_is_zero_on_a_uniform_image() {
+        let image = uniform_image(4, 4, Rgba { data: [50, 50, 50, 255] });
+        let mut carver = Carver::new(&image);
+        carver.set_energy_model(EnergyModel::Forward);
+
+        carver.calculate_energy();
+
+        for y in 0..carver.grid.height() {
+            for x in 0..carver.grid.width() {
+                assert_eq!(carver.grid.get(x, y).path_cost, 0);
+            }
+        }
+    }
+
+    // Every row repeats the same per-column values, so `SquaredGradientEnergy`
+    // only picks up the horizontal gradient and every row scores a column
+    // identically, making it easy to predict which column the cheapest seam
+    // runs through.
+    fn column_image(height: u32, column_values: &[u8]) -> DynamicImage {
+        let width = column_values.len() as u32;
+        let mut image = DynamicImage::new_rgba8(width, height);
+        for y in 0..height {
+            for (x, &value) in column_values.iter().enumerate() {
+                image.put_pixel(x as u32, y, Rgba { data: [value, value, value, 255] });
+            }
+        }
+        image
+    }
+
+    // Regression test for chunk0-2's mask plumbing: with `SquaredGradientEnergy`,
+    // column 2 is the unique cheapest seam (the only plateau; every other
+    // column's neighbors differ). Protecting it must push the cheapest seam
+    // to column 0, the next-cheapest, rather than cutting straight through it.
+    #[test]
+    fn protect_mask_steers_the_seam_away_from_the_protected_column() {
+        let image = column_image(3, &[0, 10, 40, 10, 90]);
+        let mut carver = Carver::new(&image);
+        carver.set_protect_mask(&[(2, 0), (2, 1), (2, 2)]);
+
+        carver.resize(1, Orientation::Horizontal, Mode::Shrink);
+
+        let removed = carver.get_removed_points();
+        assert_eq!(removed.len(), 3);
+        assert!(removed.iter().all(|&(x, _)| x == 0),
+                "expected the seam to avoid the protected column 2, got {:?}", removed);
+    }
+
+    // Regression test for chunk0-2's mask plumbing: column 3 is not the
+    // cheapest seam on its own, but marking it for removal should make it
+    // cheaper than every naturally-occurring column, pulling the seam
+    // through it.
+    #[test]
+    fn remove_mask_pulls_the_seam_through_the_marked_column() {
+        let image = column_image(3, &[0, 10, 30, 45, 100]);
+        let mut carver = Carver::new(&image);
+        carver.set_remove_mask(&[(3, 0), (3, 1), (3, 2)]);
+
+        carver.resize(1, Orientation::Horizontal, Mode::Shrink);
+
+        let removed = carver.get_removed_points();
+        assert_eq!(removed.len(), 3);
+        assert!(removed.iter().all(|&(x, _)| x == 3),
+                "expected the seam to run through the remove-masked column 3, got {:?}", removed);
+    }
+
+    // Regression test for chunk0-4's core claim: after removing seams the
+    // normal way (each `calculate_energy` only recomputing the cells
+    // `remove_path` marked dirty), forcing one more `calculate_energy` pass
+    // from a cache-cleared clone must land on exactly the same energy and
+    // path cost grid as the incremental path did.
+    #[test]
+    fn incremental_recompute_matches_a_full_recompute() {
+        let mut image = DynamicImage::new_rgba8(6, 6);
+        for y in 0..6 {
+            for x in 0..6 {
+                let value = ((x * 7 + y * 13) % 251) as u8;
+                image.put_pixel(x, y, Rgba { data: [value, value.wrapping_add(50), value.wrapping_add(90), 255] });
+            }
+        }
+
+        let mut incremental = Carver::new(&image);
+        incremental.resize(2, Orientation::Horizontal, Mode::Shrink);
+        incremental.calculate_energy();
+
+        let mut full = incremental.clone();
+        full.energy_computed = false;
+        full.dirty_cells.clear();
+        full.calculate_energy();
+
+        for y in 0..incremental.grid.height() {
+            for x in 0..incremental.grid.width() {
+                let from_incremental = incremental.grid.get(x, y);
+                let from_full = full.grid.get(x, y);
+                assert_eq!(from_incremental.energy, from_full.energy,
+                           "energy mismatch at ({}, {})", x, y);
+                assert_eq!(from_incremental.path_cost, from_full.path_cost,
+                           "path cost mismatch at ({}, {})", x, y);
+            }
+        }
+    }
+
+    // Sanity check for chunk0-5's `SobelEnergy`: a flat image has no
+    // gradient in any direction, so every pixel's convolution result is 0.
+    #[test]
+    fn sobel_energy_is_zero_on_a_uniform_image() {
+        let image = uniform_image(4, 4, Rgba { data: [60, 60, 60, 255] });
+        let grid: Grid<PixelEnergyPoint> = (&image).into();
+        let sobel = SobelEnergy;
+
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(sobel.energy(&grid, x, y), 0);
+            }
+        }
+    }
+
+    // Sanity check for chunk0-5's `SobelEnergy`: it should score a sharp
+    // edge higher than the interior of a flat region either side of it.
+    #[test]
+    fn sobel_energy_is_higher_at_an_edge_than_in_a_flat_region() {
+        let image = column_image(4, &[10, 10, 10, 200, 200, 200]);
+        let grid: Grid<PixelEnergyPoint> = (&image).into();
+        let sobel = SobelEnergy;
+
+        let at_edge = sobel.energy(&grid, 2, 1);
+        let in_flat_region = sobel.energy(&grid, 0, 1);
+        assert!(at_edge > in_flat_region,
+                "expected edge energy ({}) to exceed flat-region energy ({})", at_edge, in_flat_region);
+    }
+
+    // Sanity check for chunk0-5's `VarianceEnergy`: a flat window has zero
+    // variance.
+    #[test]
+    fn variance_energy_is_zero_on_a_uniform_image() {
+        let image = uniform_image(5, 5, Rgba { data: [77, 77, 77, 255] });
+        let grid: Grid<PixelEnergyPoint> = (&image).into();
+        let variance = VarianceEnergy::default();
+
+        assert_eq!(variance.energy(&grid, 2, 2), 0);
+    }
+
+    // Sanity check for chunk0-5's `VarianceEnergy`: a checkerboard window
+    // should score higher than a flat one.
+    #[test]
+    fn variance_energy_is_higher_in_a_noisy_region() {
+        let mut image = DynamicImage::new_rgba8(6, 6);
+        for y in 0..6 {
+            for x in 0..6 {
+                let value = if x < 3 {
+                    50
+                } else if (x + y) % 2 == 0 {
+                    0
+                } else {
+                    255
+                };
+                image.put_pixel(x, y, Rgba { data: [value, value, value, 255] });
+            }
+        }
+        let grid: Grid<PixelEnergyPoint> = (&image).into();
+        let variance = VarianceEnergy::default();
+
+        let flat = variance.energy(&grid, 1, 3);
+        let noisy = variance.energy(&grid, 4, 3);
+        assert!(noisy > flat,
+                "expected noisy-region variance ({}) to exceed flat-region variance ({})", noisy, flat);
+    }
+
+    // Regression test for chunk0-6's frame capture: a height-changing
+    // resize runs its seam removals through `rotate()`, and every captured
+    // frame must come back out in the original width/height orientation,
+    // not transposed.
+    #[test]
+    fn captured_frames_stay_in_the_original_orientation_during_a_height_resize() {
+        let width = 5;
+        let height = 6;
+        let image = uniform_image(width, height, Rgba { data: [40, 80, 120, 255] });
+        let mut carver = Carver::new(&image);
+        carver.set_record_frames(true, false);
+
+        carver.resize(2, Orientation::Vertical, Mode::Shrink);
+
+        let frames = carver.get_frames();
+        assert_eq!(frames.len(), 2);
+        for frame in &frames {
+            assert_eq!(frame.dimensions().0, width,
+                       "frame width should stay {} instead of coming back transposed", width);
+        }
+    }
+}